@@ -0,0 +1,182 @@
+//! Signs many values with a single HMAC over a Merkle tree, so each value
+//! still verifies independently via its audit path, as in time-stamping
+//! and roughtime-style batching.
+
+use crate::{Error, Signer};
+
+/// A signed value from a batch, carrying everything needed to verify it
+/// without access to the rest of the batch: its position in the tree and
+/// the sibling hashes along its path to the signed root.
+#[derive(Debug, PartialEq)]
+pub struct BatchToken {
+    pub value: String,
+    pub index: usize,
+    pub audit_path: Vec<Vec<u8>>,
+    pub root_signature: String,
+}
+
+/// Signs a batch of values with one HMAC over their Merkle root, amortizing
+/// the cost of signing across the whole batch. See [`BatchSigner::sign_batch`].
+pub struct BatchSigner {
+    signer: Signer,
+}
+
+impl BatchSigner {
+    pub fn new(signer: Signer) -> BatchSigner {
+        BatchSigner { signer }
+    }
+
+    /// Sign every value in `values` with a single HMAC, returning one
+    /// [`BatchToken`] per value.
+    pub fn sign_batch(&self, values: &[&str]) -> Result<Vec<BatchToken>, Error> {
+        if values.is_empty() {
+            return Err(Error::EmptyBatch);
+        }
+
+        let leaves: Vec<Vec<u8>> = values
+            .iter()
+            .map(|value| self.signer.raw_hmac(value.as_bytes()))
+            .collect();
+
+        let levels = self.build_levels(leaves);
+        let root = levels.last().unwrap()[0].clone();
+        let root_signature = self.signer.signature_bytes(&root);
+
+        Ok(values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| BatchToken {
+                value: (*value).to_owned(),
+                index,
+                audit_path: audit_path(&levels, index),
+                root_signature: root_signature.clone(),
+            })
+            .collect())
+    }
+
+    /// Verify a single [`BatchToken`] by recomputing the Merkle root from
+    /// its value and audit path, then checking the root signature.
+    pub fn verify(&self, token: &BatchToken) -> Result<(), Error> {
+        let mut hash = self.signer.raw_hmac(token.value.as_bytes());
+        let mut index = token.index;
+
+        for sibling in &token.audit_path {
+            let mut node = Vec::with_capacity(hash.len() + sibling.len());
+            if index % 2 == 0 {
+                node.extend_from_slice(&hash);
+                node.extend_from_slice(sibling);
+            } else {
+                node.extend_from_slice(sibling);
+                node.extend_from_slice(&hash);
+            }
+            hash = self.signer.raw_hmac(&node);
+            index /= 2;
+        }
+
+        self.signer.verify_bytes(&hash, &token.root_signature)
+    }
+
+    // Builds the tree bottom-up from the leaf hashes, returning every
+    // level (leaves first, root last) so audit paths can be retraced.
+    fn build_levels(&self, leaves: Vec<Vec<u8>>) -> Vec<Vec<Vec<u8>>> {
+        let mut levels = vec![leaves];
+
+        while levels.last().unwrap().len() > 1 {
+            let current = levels.last().unwrap();
+            let mut next = Vec::with_capacity((current.len() + 1) / 2);
+            let mut i = 0;
+            while i < current.len() {
+                let left = &current[i];
+                // Odd levels duplicate the last node as its own sibling.
+                let right = if i + 1 < current.len() {
+                    &current[i + 1]
+                } else {
+                    &current[i]
+                };
+
+                let mut node = Vec::with_capacity(left.len() + right.len());
+                node.extend_from_slice(left);
+                node.extend_from_slice(right);
+                next.push(self.signer.raw_hmac(&node));
+
+                i += 2;
+            }
+            levels.push(next);
+        }
+
+        levels
+    }
+}
+
+fn audit_path(levels: &[Vec<Vec<u8>>], leaf_index: usize) -> Vec<Vec<u8>> {
+    let mut path = Vec::with_capacity(levels.len() - 1);
+    let mut index = leaf_index;
+
+    for level in &levels[..levels.len() - 1] {
+        let sibling_index = if index % 2 == 0 {
+            if index + 1 < level.len() {
+                index + 1
+            } else {
+                index
+            }
+        } else {
+            index - 1
+        };
+        path.push(level[sibling_index].clone());
+        index /= 2;
+    }
+
+    path
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn every_value_in_a_batch_verifies() {
+        let batch_signer = BatchSigner::new(Signer::new(b"my-key"));
+        let values = ["one", "two", "three", "four", "five"];
+
+        let tokens = batch_signer.sign_batch(&values).unwrap();
+        assert_eq!(values.len(), tokens.len());
+
+        for token in &tokens {
+            assert_eq!(Ok(()), batch_signer.verify(token));
+        }
+    }
+
+    #[test]
+    fn single_value_batch_verifies() {
+        let batch_signer = BatchSigner::new(Signer::new(b"my-key"));
+        let tokens = batch_signer.sign_batch(&["only"]).unwrap();
+
+        assert_eq!(Ok(()), batch_signer.verify(&tokens[0]));
+    }
+
+    #[test]
+    fn tampered_value_fails_verification() {
+        let batch_signer = BatchSigner::new(Signer::new(b"my-key"));
+        let mut tokens = batch_signer.sign_batch(&["one", "two", "three"]).unwrap();
+
+        tokens[1].value = "tampered".to_owned();
+        assert_eq!(Err(Error::BadSignature), batch_signer.verify(&tokens[1]));
+    }
+
+    #[test]
+    fn tampered_audit_path_fails_verification() {
+        let batch_signer = BatchSigner::new(Signer::new(b"my-key"));
+        let mut tokens = batch_signer
+            .sign_batch(&["one", "two", "three", "four"])
+            .unwrap();
+
+        tokens[0].audit_path[0][0] ^= 0xff;
+        assert_eq!(Err(Error::BadSignature), batch_signer.verify(&tokens[0]));
+    }
+
+    #[test]
+    fn empty_batch_is_an_error() {
+        let batch_signer = BatchSigner::new(Signer::new(b"my-key"));
+        assert_eq!(Err(Error::EmptyBatch), batch_signer.sign_batch(&[]));
+    }
+}
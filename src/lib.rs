@@ -42,6 +42,21 @@
 extern crate base64;
 extern crate byteorder;
 extern crate ring;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "serde")]
+extern crate serde_json;
+
+#[cfg(feature = "serde")]
+mod serializer;
+#[cfg(feature = "serde")]
+pub use serializer::Serializer;
+
+mod stream;
+pub use stream::{SigningStream, VerifyingStream};
+
+mod batch;
+pub use batch::{BatchSigner, BatchToken};
 
 // Use same EPOCH as nobi, the Ruby implementation
 const EPOCH: u64 = 1293840000;
@@ -50,7 +65,38 @@ use base64::URL_SAFE_NO_PAD;
 use byteorder::{ByteOrder, LittleEndian};
 use ring::{digest, hmac};
 
-static ALGORITHM: &'static digest::Algorithm = &digest::SHA1;
+// The salt nobi itself used, kept as the default so existing callers of
+// `Signer::new` see no change in behavior.
+const DEFAULT_SALT: &'static str = "nobi.Signer";
+
+/// The digest algorithm used to derive keys and compute signatures.
+///
+/// `Sha1` remains the default for backwards compatibility with existing
+/// signed values, but new deployments should prefer `Sha256` or stronger.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DigestAlg {
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl DigestAlg {
+    fn algorithm(self) -> &'static digest::Algorithm {
+        match self {
+            DigestAlg::Sha1 => &digest::SHA1,
+            DigestAlg::Sha256 => &digest::SHA256,
+            DigestAlg::Sha384 => &digest::SHA384,
+            DigestAlg::Sha512 => &digest::SHA512,
+        }
+    }
+}
+
+impl Default for DigestAlg {
+    fn default() -> DigestAlg {
+        DigestAlg::Sha1
+    }
+}
 
 #[derive(Debug, PartialEq)]
 pub enum Error {
@@ -58,11 +104,102 @@ pub enum Error {
     BadSignature,
     BadTimeSignature,
     SignatureExpired,
+    EmptyBatch,
+    #[cfg(feature = "serde")]
+    SerializationFailed,
+    #[cfg(feature = "serde")]
+    DeserializationFailed,
 }
 
 pub struct Signer {
     separator: char,
     key: hmac::SigningKey,
+    algorithm: &'static digest::Algorithm,
+    fallback_keys: Vec<hmac::SigningKey>,
+}
+
+/// Builds a [`Signer`] with a non-default salt, separator, algorithm or
+/// set of fallback secrets.
+///
+/// Use [`Signer::builder`] to obtain one. The salt namespaces the derived
+/// key so that, for example, a value signed for `"activation"` can't be
+/// replayed as a valid signature for `"password-reset"`.
+pub struct SignerBuilder<'a> {
+    secret: &'a [u8],
+    salt: &'a str,
+    separator: char,
+    algorithm: DigestAlg,
+    fallback_secrets: Vec<&'a [u8]>,
+}
+
+impl<'a> SignerBuilder<'a> {
+    fn new(secret: &'a [u8]) -> SignerBuilder<'a> {
+        SignerBuilder {
+            secret,
+            salt: DEFAULT_SALT,
+            separator: '.',
+            algorithm: DigestAlg::default(),
+            fallback_secrets: Vec::new(),
+        }
+    }
+
+    /// Set the salt used to derive the signing key, namespacing it to a
+    /// particular purpose (e.g. `"activation"` vs. `"password-reset"`).
+    pub fn salt(mut self, salt: &'a str) -> SignerBuilder<'a> {
+        self.salt = salt;
+        self
+    }
+
+    /// Set the separator placed between the value and its signature.
+    ///
+    /// `unsign` finds the signature by splitting on the *last* occurrence
+    /// of this character, so avoid a separator that can itself appear in
+    /// the base64-url signature (i.e. avoid letters, digits, `-` and `_`)
+    /// — otherwise a signature containing that character is split in the
+    /// wrong place and fails to verify. The separator may appear in `value`.
+    pub fn separator(mut self, separator: char) -> SignerBuilder<'a> {
+        self.separator = separator;
+        self
+    }
+
+    /// Set the digest algorithm used to derive the key and compute
+    /// signatures.
+    pub fn algorithm(mut self, algorithm: DigestAlg) -> SignerBuilder<'a> {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Add secrets that `unsign` should still accept, tried in order after
+    /// the primary secret. Use this to roll a secret while keeping the old
+    /// one valid for a deprecation window: sign with the new secret, but
+    /// still accept signatures from outstanding links made with the old one.
+    pub fn fallback_secrets(mut self, secrets: &[&'a [u8]]) -> SignerBuilder<'a> {
+        self.fallback_secrets.extend_from_slice(secrets);
+        self
+    }
+
+    pub fn build(self) -> Signer {
+        let algorithm = self.algorithm.algorithm();
+        let salt = self.salt;
+        let derive_key = |secret: &[u8]| {
+            let initial_key = hmac::SigningKey::new(algorithm, secret);
+            let derived_key = hmac::sign(&initial_key, salt.as_bytes());
+            hmac::SigningKey::new(algorithm, derived_key.as_ref())
+        };
+
+        let fallback_keys = self
+            .fallback_secrets
+            .iter()
+            .map(|secret| derive_key(secret))
+            .collect();
+
+        Signer {
+            separator: self.separator,
+            key: derive_key(self.secret),
+            algorithm,
+            fallback_keys,
+        }
+    }
 }
 
 pub struct TimestampSigner {
@@ -82,13 +219,13 @@ fn bytes_to_int(n: &[u8]) -> i32 {
 
 impl Signer {
     pub fn new(secret: &[u8]) -> Signer {
-        let initial_key = hmac::SigningKey::new(ALGORITHM, secret);
-        let derived_key = hmac::sign(&initial_key, b"nobi.Signer");
+        Signer::builder(secret).build()
+    }
 
-        Signer {
-            separator: '.',
-            key: hmac::SigningKey::new(ALGORITHM, derived_key.as_ref()),
-        }
+    /// Start building a `Signer` with a custom salt, separator or
+    /// algorithm. See [`SignerBuilder`].
+    pub fn builder(secret: &[u8]) -> SignerBuilder {
+        SignerBuilder::new(secret)
     }
 
     pub fn sign(&self, value: &str) -> String {
@@ -108,15 +245,56 @@ impl Signer {
         };
 
         let sig = base64::decode_config(sig, URL_SAFE_NO_PAD).map_err(|_| Error::BadSignature)?;
-        hmac::verify_with_own_key(&self.key, value.as_bytes(), &sig)
-            .map_err(|_| Error::BadSignature)?;
+        if sig.len() != self.algorithm.output_len {
+            return Err(Error::BadSignature);
+        }
+
+        // Try the primary key first, then any fallback keys from a secret
+        // rotation, in the order they were configured.
+        let verifies = std::iter::once(&self.key)
+            .chain(self.fallback_keys.iter())
+            .any(|key| hmac::verify_with_own_key(key, value.as_bytes(), &sig).is_ok());
+
+        if !verifies {
+            return Err(Error::BadSignature);
+        }
 
         Ok(value.into())
     }
 
-    fn signature(&self, value: &str) -> String {
-        let sig = hmac::sign(&self.key, value.as_bytes());
-        base64::encode_config(sig.as_ref(), URL_SAFE_NO_PAD)
+    pub(crate) fn signature(&self, value: &str) -> String {
+        self.signature_bytes(value.as_bytes())
+    }
+
+    pub(crate) fn signature_bytes(&self, data: &[u8]) -> String {
+        base64::encode_config(&self.raw_hmac(data), URL_SAFE_NO_PAD)
+    }
+
+    pub(crate) fn raw_hmac(&self, data: &[u8]) -> Vec<u8> {
+        hmac::sign(&self.key, data).as_ref().to_vec()
+    }
+
+    pub(crate) fn verify_bytes(&self, data: &[u8], signature: &str) -> Result<(), Error> {
+        let sig =
+            base64::decode_config(signature, URL_SAFE_NO_PAD).map_err(|_| Error::BadSignature)?;
+        hmac::verify_with_own_key(&self.key, data, &sig).map_err(|_| Error::BadSignature)
+    }
+
+    /// Start an incremental HMAC over data fed in pieces, for values too
+    /// large to sign in one call. See [`SigningStream`].
+    pub fn signing_stream(&self) -> SigningStream {
+        SigningStream::new(&self.key)
+    }
+
+    /// Start an incremental verification of `value || signature` fed in
+    /// pieces, accepting the primary key or any fallback key from a secret
+    /// rotation, same as [`unsign`](Signer::unsign). See [`VerifyingStream`].
+    pub fn verifying_stream(&self) -> VerifyingStream {
+        let keys: Vec<&hmac::SigningKey> = std::iter::once(&self.key)
+            .chain(self.fallback_keys.iter())
+            .collect();
+
+        VerifyingStream::new(&keys, self.algorithm.output_len)
     }
 }
 
@@ -192,7 +370,6 @@ impl TimestampSigner {
 
 #[cfg(test)]
 mod test {
-    use super::ALGORITHM;
     use super::*;
 
     #[test]
@@ -247,11 +424,133 @@ mod test {
         assert_eq!(Err(Error::SignatureExpired), signer.unsign(&signed, 0));
     }
 
+    #[test]
+    fn salts_namespace_the_derived_key() {
+        let activation = Signer::builder(b"my-key").salt("activation").build();
+        let password_reset = Signer::builder(b"my-key").salt("password-reset").build();
+
+        let signed = activation.sign("101");
+        assert_eq!(Err(Error::BadSignature), password_reset.unsign(&signed));
+        assert_eq!("101".to_owned(), activation.unsign(&signed).unwrap());
+    }
+
+    #[test]
+    fn custom_separator_round_trips() {
+        let signer = Signer::builder(b"my-key").separator(':').build();
+
+        let signed = signer.sign("value");
+        assert_eq!("value".to_owned(), signer.unsign(&signed).unwrap());
+        assert!(!signed.contains('.'));
+    }
+
+    #[test]
+    fn default_salt_matches_signer_new() {
+        let builder_signer = Signer::builder(b"my-key").build();
+        let new_signer = Signer::new(b"my-key");
+
+        assert_eq!(builder_signer.sign("value"), new_signer.sign("value"));
+    }
+
+    #[test]
+    fn signs_with_sha256() {
+        let sha1_signer = Signer::new(b"my-key");
+        let sha256_signer = Signer::builder(b"my-key").algorithm(DigestAlg::Sha256).build();
+
+        let signed = sha256_signer.sign("value");
+        assert_eq!("value".to_owned(), sha256_signer.unsign(&signed).unwrap());
+
+        // A signature produced under one algorithm must not verify under another.
+        assert_eq!(Err(Error::BadSignature), sha1_signer.unsign(&signed));
+    }
+
+    #[test]
+    fn signing_stream_matches_signature() {
+        let signer = Signer::new(b"my-key");
+
+        let mut stream = signer.signing_stream();
+        stream.update(b"val");
+        stream.update(b"ue");
+
+        assert_eq!(signer.sign("value"), format!("value.{}", stream.finalize()));
+    }
+
+    #[test]
+    fn verifying_stream_accepts_matching_value_and_signature() {
+        let signer = Signer::new(b"my-key");
+        let signed = signer.sign("value");
+        let (value, sig) = signed.split_at(signed.rfind('.').unwrap());
+        let sig = &sig[1..];
+
+        let mut stream = signer.verifying_stream();
+        stream.update(value.as_bytes());
+        stream.update(sig.as_bytes());
+
+        assert_eq!(Ok(()), stream.finalize());
+    }
+
+    #[test]
+    fn verifying_stream_rejects_bad_signature() {
+        let signer = Signer::new(b"my-key");
+
+        let mut stream = signer.verifying_stream();
+        stream.update(b"value");
+        stream.update(b"AAAAAAAAAAAAAAAAAAAAAAAAAAA");
+
+        assert_eq!(Err(Error::BadSignature), stream.finalize());
+    }
+
+    #[test]
+    fn verifying_stream_accepts_a_rotated_out_key_as_fallback() {
+        let old_signer = Signer::new(b"old-key");
+        let signed_with_old_key = old_signer.sign("value");
+        let (value, sig) = signed_with_old_key.split_at(signed_with_old_key.rfind('.').unwrap());
+        let sig = &sig[1..];
+
+        let rotated_signer = Signer::builder(b"new-key")
+            .fallback_secrets(&[b"old-key"])
+            .build();
+
+        let mut stream = rotated_signer.verifying_stream();
+        stream.update(value.as_bytes());
+        stream.update(sig.as_bytes());
+
+        assert_eq!(Ok(()), stream.finalize());
+    }
+
+    #[test]
+    fn fallback_secrets_accept_links_from_a_rotated_key() {
+        let old_signer = Signer::new(b"old-key");
+        let signed_with_old_key = old_signer.sign("value");
+
+        let rotated_signer = Signer::builder(b"new-key")
+            .fallback_secrets(&[b"old-key"])
+            .build();
+
+        assert_eq!(
+            "value".to_owned(),
+            rotated_signer.unsign(&signed_with_old_key).unwrap()
+        );
+        assert_eq!(
+            "value".to_owned(),
+            rotated_signer.unsign(&rotated_signer.sign("value")).unwrap()
+        );
+    }
+
+    #[test]
+    fn unrelated_keys_are_still_rejected() {
+        let signer = Signer::builder(b"new-key")
+            .fallback_secrets(&[b"old-key"])
+            .build();
+
+        let signed = Signer::new(b"some-other-key").sign("value");
+        assert_eq!(Err(Error::BadSignature), signer.unsign(&signed));
+    }
+
     #[test]
     fn with_secure_secret() {
         use ring::rand::{SecureRandom, SystemRandom};
         let sys_rand = SystemRandom::new();
-        let mut key = vec![0u8; ALGORITHM.output_len];
+        let mut key = vec![0u8; DigestAlg::default().algorithm().output_len];
         sys_rand.fill(&mut key).unwrap();
 
         let signer = Signer::new(&key);
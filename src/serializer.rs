@@ -0,0 +1,94 @@
+//! Signs arbitrary `Serialize`/`Deserialize` payloads instead of plain
+//! `&str` values, mirroring itsdangerous's `URLSafeSerializer`.
+
+use std::marker::PhantomData;
+
+use base64::URL_SAFE_NO_PAD;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::{Error, Signer};
+
+/// Wraps a [`Signer`] to sign and verify JSON-serializable values instead
+/// of raw strings.
+///
+/// # Example:
+///
+/// ```rust,ignore
+/// use nobsign::{Signer, Serializer};
+///
+/// #[derive(serde::Serialize, serde::Deserialize)]
+/// struct Activation { user_id: u64 }
+///
+/// let serializer: Serializer<Activation> = Serializer::new(Signer::new(b"my secret"));
+/// let signed = serializer.serialize(&Activation { user_id: 101 }).unwrap();
+/// let activation = serializer.deserialize(&signed).unwrap();
+/// ```
+pub struct Serializer<T> {
+    signer: Signer,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Serializer<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    pub fn new(signer: Signer) -> Serializer<T> {
+        Serializer {
+            signer,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn serialize(&self, value: &T) -> Result<String, Error> {
+        let json = serde_json::to_vec(value).map_err(|_| Error::SerializationFailed)?;
+        let encoded = base64::encode_config(&json, URL_SAFE_NO_PAD);
+
+        Ok(self.signer.sign(&encoded))
+    }
+
+    pub fn deserialize(&self, value: &str) -> Result<T, Error> {
+        let encoded = self.signer.unsign(value)?;
+        let json =
+            base64::decode_config(&encoded, URL_SAFE_NO_PAD).map_err(|_| Error::BadData)?;
+
+        serde_json::from_slice(&json).map_err(|_| Error::DeserializationFailed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, serde::Deserialize)]
+    struct Activation {
+        user_id: u64,
+        scopes: Vec<String>,
+    }
+
+    #[test]
+    fn round_trips_a_struct() {
+        let serializer: Serializer<Activation> = Serializer::new(Signer::new(b"my-key"));
+        let activation = Activation {
+            user_id: 101,
+            scopes: vec!["activate".to_owned()],
+        };
+
+        let signed = serializer.serialize(&activation).unwrap();
+        assert_eq!(activation, serializer.deserialize(&signed).unwrap());
+    }
+
+    #[test]
+    fn rejects_tampered_payload() {
+        let serializer: Serializer<Activation> = Serializer::new(Signer::new(b"my-key"));
+        let activation = Activation {
+            user_id: 101,
+            scopes: vec![],
+        };
+
+        let mut signed = serializer.serialize(&activation).unwrap();
+        signed.push('x');
+
+        assert_eq!(Err(Error::BadSignature), serializer.deserialize(&signed));
+    }
+}
@@ -0,0 +1,103 @@
+//! Incremental HMAC signing for values too large to hold in memory at
+//! once, modeled on AWS4 chunked payload signing: init a context, feed it
+//! data in pieces, then finalize.
+
+use base64::URL_SAFE_NO_PAD;
+use ring::{constant_time, hmac};
+
+use crate::Error;
+
+/// Incrementally computes a signature over data fed via [`update`], built
+/// from [`Signer::signing_stream`](crate::Signer::signing_stream).
+///
+/// [`update`]: SigningStream::update
+pub struct SigningStream {
+    context: hmac::SigningContext,
+}
+
+impl SigningStream {
+    pub(crate) fn new(key: &hmac::SigningKey) -> SigningStream {
+        SigningStream {
+            context: hmac::SigningContext::with_key(key),
+        }
+    }
+
+    /// Feed the next chunk of the value into the running HMAC.
+    pub fn update(&mut self, data: &[u8]) {
+        self.context.update(data);
+    }
+
+    /// Consume the stream and return the base64-url signature of
+    /// everything fed to it.
+    pub fn finalize(self) -> String {
+        let sig = self.context.sign();
+        base64::encode_config(sig.as_ref(), URL_SAFE_NO_PAD)
+    }
+}
+
+/// Incrementally verifies a value followed by its trailing base64-url
+/// signature, without buffering the whole value in memory.
+///
+/// Built from [`Signer::verifying_stream`](crate::Signer::verifying_stream),
+/// which seeds one context per accepted key (the primary secret, then any
+/// fallback secrets from key rotation), so this agrees with
+/// [`Signer::unsign`](crate::Signer::unsign) about which signatures verify.
+/// Feed it the value bytes immediately followed by the signature bytes,
+/// in order, then call [`finalize`](VerifyingStream::finalize).
+pub struct VerifyingStream {
+    contexts: Vec<hmac::SigningContext>,
+    trailer: Vec<u8>,
+    trailer_len: usize,
+}
+
+impl VerifyingStream {
+    pub(crate) fn new(keys: &[&hmac::SigningKey], signature_len: usize) -> VerifyingStream {
+        let trailer_len = base64::encode_config(&vec![0u8; signature_len], URL_SAFE_NO_PAD).len();
+
+        VerifyingStream {
+            contexts: keys
+                .iter()
+                .map(|key| hmac::SigningContext::with_key(key))
+                .collect(),
+            trailer: Vec::with_capacity(trailer_len),
+            trailer_len,
+        }
+    }
+
+    /// Feed the next chunk of `value || signature` into the stream. Bytes
+    /// are only HMACed once enough trailing bytes have arrived behind them
+    /// to know they aren't part of the signature.
+    pub fn update(&mut self, data: &[u8]) {
+        self.trailer.extend_from_slice(data);
+
+        if self.trailer.len() > self.trailer_len {
+            let overflow = self.trailer.len() - self.trailer_len;
+            let to_sign: Vec<u8> = self.trailer.drain(..overflow).collect();
+            for context in &mut self.contexts {
+                context.update(&to_sign);
+            }
+        }
+    }
+
+    /// Consume the stream, verifying the trailing signature against the
+    /// HMAC of everything that preceded it, trying the primary key first
+    /// and then any fallback keys in order.
+    pub fn finalize(self) -> Result<(), Error> {
+        if self.trailer.len() != self.trailer_len {
+            return Err(Error::BadSignature);
+        }
+
+        let expected =
+            base64::decode_config(&self.trailer, URL_SAFE_NO_PAD).map_err(|_| Error::BadSignature)?;
+
+        let verifies = self.contexts.into_iter().any(|context| {
+            constant_time::verify_slices_are_equal(context.sign().as_ref(), &expected).is_ok()
+        });
+
+        if !verifies {
+            return Err(Error::BadSignature);
+        }
+
+        Ok(())
+    }
+}